@@ -0,0 +1,50 @@
+use bytes::{Bytes, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use redis_reimplementation_rust::resp::{encode_resp, encode_resp_into, RespValue};
+
+fn sample_values() -> Vec<RespValue> {
+    vec![
+        RespValue::SimpleString("OK".to_string()),
+        RespValue::BulkString(Some(Bytes::from(vec![b'x'; 64 * 1024]))),
+        RespValue::Array(Some(
+            (0..32)
+                .map(|i| RespValue::BulkString(Some(Bytes::from(format!("field-{i}")))))
+                .collect(),
+        )),
+        // A deeply nested array (10 levels, one bulk string at the
+        // bottom) to exercise the recursive copy-up behavior that
+        // `encode_resp`'s allocating path was criticized for.
+        (0..9).fold(
+            RespValue::Array(Some(vec![RespValue::BulkString(Some(Bytes::from_static(
+                b"leaf",
+            )))])),
+            |inner, _| RespValue::Array(Some(vec![inner])),
+        ),
+    ]
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let values = sample_values();
+
+    c.bench_function("encode_resp (allocates per call)", |b| {
+        b.iter(|| {
+            for val in &values {
+                black_box(encode_resp(black_box(val)));
+            }
+        })
+    });
+
+    c.bench_function("encode_resp_into (reused buffer)", |b| {
+        let mut out = BytesMut::with_capacity(128 * 1024);
+        b.iter(|| {
+            for val in &values {
+                out.clear();
+                encode_resp_into(black_box(val), &mut out);
+                black_box(&out);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_encode);
+criterion_main!(benches);
@@ -0,0 +1,249 @@
+use bytes::{Buf, BytesMut};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::RespError;
+
+/// Compare an `AUTH` password against the configured one in constant time.
+/// `==` on `str` short-circuits on the first mismatched byte, which leaks
+/// how much of the guess was right through response timing; the whole point
+/// of `requirepass` gating the AEAD session upgrade is to raise the bar on
+/// transport security, so the comparison itself shouldn't hand back a side
+/// channel. A length mismatch is its own (constant-time, since both lengths
+/// are known to the attacker anyway) rejection.
+pub(crate) fn verify_password(expected: &str, candidate: &str) -> bool {
+    expected.len() == candidate.len() && expected.as_bytes().ct_eq(candidate.as_bytes()).into()
+}
+
+/// Size, in bytes, of the random nonce the server sends back on a
+/// successful `AUTH <password> UPGRADE`. It's mixed into the shared
+/// password to derive the session key, so two connections authenticating
+/// with the same password still end up with different keys.
+pub(crate) const SERVER_NONCE_LEN: usize = 16;
+
+/// Derive a 256-bit ChaCha20-Poly1305 key from the shared password, the
+/// server's per-connection nonce, and a direction label:
+/// `key = SHA-256(password || nonce || direction)`. The direction label
+/// (`b"c2s"` or `b"s2c"`) domain-separates the two directions of a session
+/// so they never share a key, even though each keeps its own
+/// independently-starting sequence counter.
+fn derive_key(password: &[u8], server_nonce: &[u8], direction: &[u8]) -> Key {
+    let mut hasher = Sha256::new();
+    hasher.update(password);
+    hasher.update(server_nonce);
+    hasher.update(direction);
+    *Key::from_slice(&hasher.finalize())
+}
+
+/// Seals every record exchanged after a successful encrypted `AUTH` upgrade
+/// in a ChaCha20-Poly1305 AEAD, so the bytes on the wire are confidential
+/// and tamper-evident. The two directions are keyed separately (see
+/// `derive_key`) and each keeps its own monotonically increasing `u64`
+/// counter, written little-endian into the last 8 bytes of the 12-byte nonce
+/// (the first 4 are zero), so as long as a direction
+/// never exchanges more than 2^64 records the nonce never repeats *and*
+/// the two directions can never end up encrypting under the same
+/// (key, nonce) pair.
+pub(crate) struct SessionCipher {
+    read_cipher: ChaCha20Poly1305,
+    write_cipher: ChaCha20Poly1305,
+    read_seq: u64,
+    write_seq: u64,
+}
+
+impl SessionCipher {
+    pub(crate) fn new(password: &[u8], server_nonce: &[u8]) -> Self {
+        SessionCipher {
+            read_cipher: ChaCha20Poly1305::new(&derive_key(password, server_nonce, b"c2s")),
+            write_cipher: ChaCha20Poly1305::new(&derive_key(password, server_nonce, b"s2c")),
+            read_seq: 0,
+            write_seq: 0,
+        }
+    }
+
+    fn nonce_for(seq: u64) -> Nonce {
+        let mut bytes = [0u8; 12];
+        bytes[4..].copy_from_slice(&seq.to_le_bytes());
+        *Nonce::from_slice(&bytes)
+    }
+
+    /// Seal `plaintext` as the next outbound record.
+    pub(crate) fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, RespError> {
+        let nonce = Self::nonce_for(self.write_seq);
+        self.write_seq += 1;
+        self.write_cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| RespError::Protocol("session encryption failure".into()))
+    }
+
+    /// Open the next inbound record's ciphertext (including its AEAD tag).
+    pub(crate) fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, RespError> {
+        let nonce = Self::nonce_for(self.read_seq);
+        self.read_seq += 1;
+        self.read_cipher
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| RespError::Protocol("session decryption/authentication failure".into()))
+    }
+}
+
+/// Move fully-received bytes from `raw` into `plain`. With no session
+/// cipher this is a plain pass-through; once upgraded, `raw` instead holds a
+/// stream of `[u32 little-endian length][ciphertext+tag]` records, and each
+/// complete one is decrypted before its plaintext is appended to `plain`.
+/// Leaves a trailing partial record (or partial length prefix) in `raw` for
+/// the next read.
+pub(crate) fn drain_records(
+    raw: &mut BytesMut,
+    cipher: &mut Option<SessionCipher>,
+    plain: &mut BytesMut,
+) -> Result<(), RespError> {
+    let cipher = match cipher {
+        Some(cipher) => cipher,
+        None => {
+            plain.extend_from_slice(raw);
+            raw.clear();
+            return Ok(());
+        }
+    };
+
+    loop {
+        if raw.len() < 4 {
+            break;
+        }
+        let len = u32::from_le_bytes(raw[..4].try_into().unwrap()) as usize;
+        if raw.len() < 4 + len {
+            break;
+        }
+        raw.advance(4);
+        let ciphertext = raw.split_to(len);
+        let plaintext = cipher.decrypt(&ciphertext)?;
+        plain.extend_from_slice(&plaintext);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &[u8] = b"hunter2";
+    const NONCE: [u8; SERVER_NONCE_LEN] = [7u8; SERVER_NONCE_LEN];
+
+    /// Frame an already-sealed `record` the same way `send` does on the
+    /// wire: a little-endian `u32` byte count followed by the sealed bytes.
+    fn framed(record: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + record.len());
+        out.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        out.extend_from_slice(record);
+        out
+    }
+
+    /// A cipher keyed the way the *other* end of a `SessionCipher` session
+    /// would be: what we send (`s2c`) is what it reads, and vice versa.
+    /// `SessionCipher` itself only ever models one side, so exercising the
+    /// wire format needs this stand-in for the peer.
+    fn peer_cipher(direction_we_read: &[u8]) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&derive_key(PASSWORD, &NONCE, direction_we_read))
+    }
+
+    /// Seal `plaintext` as the `seq`'th record a peer sends *to* the server,
+    /// i.e. under the same (`c2s`, seq) key/nonce pair `SessionCipher::decrypt`
+    /// will expect it under.
+    fn seal_inbound(plaintext: &[u8], seq: u64) -> Vec<u8> {
+        let nonce = SessionCipher::nonce_for(seq);
+        peer_cipher(b"c2s").encrypt(&nonce, plaintext).unwrap()
+    }
+
+    #[test]
+    fn encrypt_round_trips_through_the_peer_holding_matching_keys() {
+        let mut server = SessionCipher::new(PASSWORD, &NONCE);
+        let peer_read = peer_cipher(b"s2c");
+
+        let sealed = server.encrypt(b"hello").unwrap();
+        let nonce = SessionCipher::nonce_for(0);
+        let opened = peer_read.decrypt(&nonce, sealed.as_slice()).unwrap();
+        assert_eq!(opened, b"hello");
+    }
+
+    #[test]
+    fn decrypt_rejects_a_tampered_ciphertext() {
+        let mut sealed = seal_inbound(b"AUTH payload", 0);
+        *sealed.last_mut().unwrap() ^= 0x01;
+
+        let mut server = SessionCipher::new(PASSWORD, &NONCE);
+        let err = server.decrypt(&sealed).unwrap_err();
+        assert!(matches!(err, RespError::Protocol(_)));
+    }
+
+    #[test]
+    fn decrypt_rejects_a_record_played_out_of_its_sequence_order() {
+        // The nonce is derived purely from the monotonic sequence counter,
+        // so a record sealed for seq 1 must not verify against the seq-0
+        // nonce a fresh `decrypt` expects -- i.e. a replayed or reordered
+        // record is rejected, not silently accepted.
+        let second = seal_inbound(b"two", 1);
+
+        let mut server = SessionCipher::new(PASSWORD, &NONCE);
+        let err = server.decrypt(&second).unwrap_err();
+        assert!(matches!(err, RespError::Protocol(_)));
+    }
+
+    #[test]
+    fn drain_records_resumes_when_split_inside_the_length_prefix() {
+        let sealed = seal_inbound(b"PING", 0);
+        let wire = framed(&sealed);
+
+        let mut cipher = Some(SessionCipher::new(PASSWORD, &NONCE));
+        let mut raw = BytesMut::from(&wire[..2]);
+        let mut plain = BytesMut::new();
+        drain_records(&mut raw, &mut cipher, &mut plain).unwrap();
+        assert!(
+            plain.is_empty(),
+            "a split length prefix must not be mistaken for a ready record"
+        );
+        assert_eq!(raw.len(), 2, "the partial prefix must stay buffered untouched");
+
+        raw.extend_from_slice(&wire[2..]);
+        drain_records(&mut raw, &mut cipher, &mut plain).unwrap();
+        assert_eq!(plain.as_ref(), b"PING");
+        assert!(raw.is_empty());
+    }
+
+    #[test]
+    fn drain_records_resumes_when_split_inside_the_ciphertext() {
+        let sealed = seal_inbound(b"a longer PING payload", 0);
+        let wire = framed(&sealed);
+        let split_at = wire.len() - 3;
+
+        let mut cipher = Some(SessionCipher::new(PASSWORD, &NONCE));
+        let mut raw = BytesMut::from(&wire[..split_at]);
+        let mut plain = BytesMut::new();
+        drain_records(&mut raw, &mut cipher, &mut plain).unwrap();
+        assert!(plain.is_empty());
+        assert_eq!(raw.len(), split_at);
+
+        raw.extend_from_slice(&wire[split_at..]);
+        drain_records(&mut raw, &mut cipher, &mut plain).unwrap();
+        assert_eq!(plain.as_ref(), b"a longer PING payload");
+    }
+
+    #[test]
+    fn drain_records_passes_plaintext_through_untouched_with_no_cipher() {
+        let mut cipher = None;
+        let mut raw = BytesMut::from(&b"*1\r\n$4\r\nPING\r\n"[..]);
+        let mut plain = BytesMut::new();
+        drain_records(&mut raw, &mut cipher, &mut plain).unwrap();
+        assert_eq!(plain.as_ref(), &b"*1\r\n$4\r\nPING\r\n"[..]);
+        assert!(raw.is_empty());
+    }
+
+    #[test]
+    fn verify_password_is_true_only_for_an_exact_match() {
+        assert!(verify_password("hunter2", "hunter2"));
+        assert!(!verify_password("hunter2", "hunter3"));
+        assert!(!verify_password("hunter2", "hunter22"));
+        assert!(!verify_password("hunter2", ""));
+    }
+}
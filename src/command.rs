@@ -1,16 +1,60 @@
-use crate::{Db, RespError, RespValue};
+use std::fmt;
+
+use bytes::{Bytes, BytesMut};
+use redis_reimplementation_rust::resp::RespValue;
+use tokio::sync::mpsc::Receiver;
+
+use crate::{Db, RespError};
+
+/// Raw chunks of a large `SET`'s value arriving from the socket, in place of
+/// one contiguous `Bytes`, once the declared length reaches
+/// `Config::stream_threshold`. `main::peek_streamed_set` detects such a
+/// value from its header alone and `main::feed_streamed_value` drives the
+/// sending half, so the body never has to land in one buffer before
+/// `Command::execute` can run.
+pub type ByteStream = Receiver<Bytes>;
+
+/// A `SET`'s value: either already fully in memory (the common case, built
+/// by `Command::from_frame`) or arriving incrementally through a
+/// `ByteStream` (built directly by `handle_connection` for large values).
+pub enum SetValue {
+    Whole(Bytes),
+    Streamed(ByteStream),
+}
+
+impl fmt::Debug for SetValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetValue::Whole(b) => f.debug_tuple("Whole").field(b).finish(),
+            SetValue::Streamed(_) => f.debug_tuple("Streamed").field(&"<channel>").finish(),
+        }
+    }
+}
 
 /// High-level representation of supported commands.
 #[derive(Debug)]
 pub enum Command {
     Ping,
     Get(String),
-    Set { key: String, value: String },
+    Set { key: String, value: SetValue },
+    /// `AUTH <password> [UPGRADE]`. `upgrade` requests the post-auth
+    /// encrypted session handshake; both the password check and the
+    /// handshake itself need per-connection state `execute` doesn't have
+    /// access to, so `handle_connection` intercepts this variant before it
+    /// would otherwise reach `execute`.
+    Auth { password: String, upgrade: bool },
 }
 
 impl Command {
-    /// Deserialize a RESP value (typically an array) into a high-level `Command`.
-    pub fn from_resp(frame: RespValue) -> Result<Self, RespError> {
+    /// Deserialize a decoded value (typically an array) into a high-level
+    /// `Command`, regardless of which wire format produced it: both the RESP
+    /// parser and the Skyhash binary parser decode into the same
+    /// `RespValue`, so this is the single place command shape is validated.
+    /// Always produces a `SetValue::Whole` — `RespValue` only ever
+    /// represents a frame that's already fully decoded, so a `SET` large
+    /// enough to stream is built directly by `handle_connection` instead of
+    /// going through here.
+    pub fn from_frame(frame: RespValue) -> Result<Self, RespError> {
         let items = match frame {
             RespValue::Array(Some(items)) if !items.is_empty() => items,
             _ => {
@@ -33,6 +77,7 @@ impl Command {
             "PING" => Ok(Command::Ping),
             "GET" => Self::from_get(&items[1..]),
             "SET" => Self::from_set(&items[1..]),
+            "AUTH" => Self::from_auth(&items[1..]),
             other => Err(RespError::Protocol(format!(
                 "unknown command '{}'",
                 other
@@ -72,21 +117,63 @@ impl Command {
             }
         };
         let value = match &args[1] {
-            RespValue::BulkString(Some(b)) => String::from_utf8_lossy(b).to_string(),
+            RespValue::BulkString(Some(b)) => b.clone(),
             _ => {
                 return Err(RespError::Protocol(
                     "invalid value type for 'SET' (expected bulk string)".into(),
                 ))
             }
         };
-        Ok(Command::Set { key, value })
+        Ok(Command::Set {
+            key,
+            value: SetValue::Whole(value),
+        })
+    }
+
+    fn from_auth(args: &[RespValue]) -> Result<Self, RespError> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(RespError::Protocol(
+                "wrong number of arguments for 'AUTH'".into(),
+            ));
+        }
+        let password = match &args[0] {
+            RespValue::BulkString(Some(b)) => String::from_utf8_lossy(b).to_string(),
+            _ => {
+                return Err(RespError::Protocol(
+                    "invalid password type for 'AUTH' (expected bulk string)".into(),
+                ))
+            }
+        };
+        let upgrade = match args.get(1) {
+            None => false,
+            Some(RespValue::BulkString(Some(b))) if b.eq_ignore_ascii_case(b"UPGRADE") => true,
+            Some(_) => {
+                return Err(RespError::Protocol(
+                    "unknown AUTH option (expected UPGRADE)".into(),
+                ))
+            }
+        };
+        Ok(Command::Auth { password, upgrade })
     }
 
     /// Execute the command against the in-memory DB and serialize as RESP.
-    pub fn execute(self, db: &Db) -> RespValue {
+    /// Async because a streamed `SET` has to drain its `ByteStream` to
+    /// completion before there's a value to store; everything else resolves
+    /// without ever yielding.
+    pub async fn execute(self, db: &Db) -> RespValue {
         match self {
             Command::Ping => RespValue::SimpleString("PONG".into()),
             Command::Set { key, value } => {
+                let value = match value {
+                    SetValue::Whole(b) => b,
+                    SetValue::Streamed(mut rx) => {
+                        let mut collected = BytesMut::new();
+                        while let Some(chunk) = rx.recv().await {
+                            collected.extend_from_slice(&chunk);
+                        }
+                        collected.freeze()
+                    }
+                };
                 let mut guard = db.lock().unwrap();
                 guard.insert(key, value);
                 RespValue::SimpleString("OK".into())
@@ -94,12 +181,16 @@ impl Command {
             Command::Get(key) => {
                 let guard = db.lock().unwrap();
                 match guard.get(&key) {
-                    Some(v) => RespValue::BulkString(Some(v.clone().into_bytes())),
+                    Some(v) => RespValue::BulkString(Some(v.clone())),
                     None => RespValue::BulkString(None),
                 }
             }
+            // `handle_connection` always intercepts `AUTH` itself (it needs
+            // `Config::requirepass` and connection-local session state), so
+            // this path is unreachable in practice.
+            Command::Auth { .. } => {
+                RespValue::Error("ERR AUTH must be handled by the connection layer".into())
+            }
         }
     }
 }
-
-
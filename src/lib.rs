@@ -0,0 +1,3 @@
+//! Exposes the wire-format internals as a library so `benches/` can measure
+//! them directly; the actual server lives in the `main.rs` binary.
+pub mod resp;
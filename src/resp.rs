@@ -0,0 +1,164 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// A decoded (or to-be-encoded) RESP value.
+#[derive(Debug)]
+pub enum RespValue {
+    SimpleString(String),
+    BulkString(Option<Bytes>),
+    Array(Option<Vec<RespValue>>),
+    Error(String),
+}
+
+/// Upper bound on how many elements a wire-supplied array (RESP or Skyhash)
+/// may claim. No real command comes anywhere near this many arguments, so
+/// this exists purely to cap the allocation a single short length prefix
+/// can force a parser into attempting -- without it, `*100000000000\r\n` or
+/// a 6-byte Skyhash array frame is enough to make `Vec::with_capacity`
+/// abort the whole process (Rust's default `handle_alloc_error` hook kills
+/// the process, not just the one connection) long before a single element
+/// has actually arrived.
+pub const MAX_ARRAY_LEN: usize = 1 << 20;
+
+/// Validate a decoded array length before any allocation happens. Shared by
+/// the RESP and Skyhash array parsers so the cap can't regress independently
+/// in one of them.
+pub fn checked_array_len(len: usize) -> Result<usize, &'static str> {
+    if len > MAX_ARRAY_LEN {
+        Err("array length exceeds the maximum allowed")
+    } else {
+        Ok(len)
+    }
+}
+
+/// Encode a value into a freshly allocated buffer. Kept around as the
+/// baseline for the `encode_resp` benchmark; production code should prefer
+/// `encode_resp_into`, which reuses a caller-owned buffer across responses.
+pub fn encode_resp(val: &RespValue) -> Vec<u8> {
+    match val {
+        RespValue::SimpleString(s) => format!("+{}\r\n", s).into_bytes(),
+        RespValue::Error(e) => format!("-{}\r\n", e).into_bytes(),
+        RespValue::BulkString(Some(b)) => {
+            let mut out = format!("${}\r\n", b.len()).into_bytes();
+            out.extend_from_slice(b);
+            out.extend_from_slice(b"\r\n");
+            out
+        }
+        RespValue::BulkString(None) => b"$-1\r\n".to_vec(),
+        RespValue::Array(Some(items)) => {
+            let mut out = format!("*{}\r\n", items.len()).into_bytes();
+            for item in items {
+                out.extend_from_slice(&encode_resp(item));
+            }
+            out
+        }
+        RespValue::Array(None) => b"*-1\r\n".to_vec(),
+    }
+}
+
+/// Encode a value directly into `out` without allocating: lengths are
+/// formatted with `itoa` instead of `format!`, and nested arrays write their
+/// elements straight into the same buffer instead of building one per level
+/// and copying it up. Callers reuse `out` across responses by clearing it
+/// (`BytesMut::clear`) rather than reallocating.
+pub fn encode_resp_into(val: &RespValue, out: &mut BytesMut) {
+    match val {
+        RespValue::SimpleString(s) => {
+            out.put_u8(b'+');
+            out.extend_from_slice(s.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+        RespValue::Error(e) => {
+            out.put_u8(b'-');
+            out.extend_from_slice(e.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+        RespValue::BulkString(Some(b)) => {
+            out.put_u8(b'$');
+            put_usize(out, b.len());
+            out.extend_from_slice(b"\r\n");
+            out.extend_from_slice(b);
+            out.extend_from_slice(b"\r\n");
+        }
+        RespValue::BulkString(None) => out.extend_from_slice(b"$-1\r\n"),
+        RespValue::Array(Some(items)) => {
+            out.put_u8(b'*');
+            put_usize(out, items.len());
+            out.extend_from_slice(b"\r\n");
+            for item in items {
+                encode_resp_into(item, out);
+            }
+        }
+        RespValue::Array(None) => out.extend_from_slice(b"*-1\r\n"),
+    }
+}
+
+/// Format `n` with `itoa` and append it to `out` without going through a
+/// `String`.
+fn put_usize(out: &mut BytesMut, n: usize) {
+    let mut buf = itoa::Buffer::new();
+    out.extend_from_slice(buf.format(n).as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assert `encode_resp_into` produces byte-for-byte the same wire
+    /// output as the allocating baseline it's meant to replace.
+    fn assert_matches_baseline(val: &RespValue) {
+        let mut out = BytesMut::new();
+        encode_resp_into(val, &mut out);
+        assert_eq!(out.as_ref(), encode_resp(val).as_slice());
+    }
+
+    #[test]
+    fn encode_resp_into_matches_encode_resp_for_simple_string() {
+        assert_matches_baseline(&RespValue::SimpleString("OK".into()));
+    }
+
+    #[test]
+    fn encode_resp_into_matches_encode_resp_for_error() {
+        assert_matches_baseline(&RespValue::Error("ERR boom".into()));
+    }
+
+    #[test]
+    fn encode_resp_into_matches_encode_resp_for_bulk_string() {
+        assert_matches_baseline(&RespValue::BulkString(Some(Bytes::from_static(b"hello"))));
+    }
+
+    #[test]
+    fn encode_resp_into_matches_encode_resp_for_null_bulk_string() {
+        assert_matches_baseline(&RespValue::BulkString(None));
+    }
+
+    #[test]
+    fn encode_resp_into_matches_encode_resp_for_null_array() {
+        assert_matches_baseline(&RespValue::Array(None));
+    }
+
+    #[test]
+    fn encode_resp_into_matches_encode_resp_for_nested_array() {
+        // An array of arrays -- exactly the shape that needs the recursive
+        // call in `encode_resp_into` to write straight into the shared
+        // buffer instead of allocating and copying a sub-buffer up.
+        let val = RespValue::Array(Some(vec![
+            RespValue::Array(Some(vec![
+                RespValue::BulkString(Some(Bytes::from_static(b"SET"))),
+                RespValue::BulkString(Some(Bytes::from_static(b"foo"))),
+            ])),
+            RespValue::Array(Some(vec![RespValue::BulkString(None)])),
+            RespValue::BulkString(Some(Bytes::from_static(b"bar"))),
+        ]));
+        assert_matches_baseline(&val);
+    }
+
+    #[test]
+    fn encode_resp_into_appends_rather_than_overwrites() {
+        // Callers reuse `out` across responses by clearing it, but
+        // `encode_resp_into` itself should only ever append -- never assume
+        // it owns the whole buffer.
+        let mut out = BytesMut::from(&b"prefix"[..]);
+        encode_resp_into(&RespValue::SimpleString("OK".into()), &mut out);
+        assert_eq!(out.as_ref(), b"prefix+OK\r\n" as &[u8]);
+    }
+}
@@ -0,0 +1,186 @@
+//! A compact, length-prefixed binary wire format that can be spoken instead
+//! of RESP. Inspired by Skytable's Skyhash: every frame is a 1-byte type tag
+//! plus (for variable-length tags) a little-endian `u32` byte count and the
+//! raw payload, so decoding never parses decimal text or scans for `\r\n`.
+//! Connections are sniffed by `MAGIC` on the first byte of a frame in
+//! `try_parse_frame`, and both wire formats decode into the same `RespValue`
+//! so `Command::from_frame` / `execute` don't need to know which one a
+//! request arrived on.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use redis_reimplementation_rust::resp::{checked_array_len, RespValue};
+
+use crate::RespError;
+
+/// Leading byte that marks a frame as Skyhash rather than RESP. RESP frames
+/// always start with one of `*$+-`, all well below this value, so a single
+/// byte unambiguously picks the decoder.
+pub(crate) const MAGIC: u8 = 0xFE;
+
+pub(crate) const TAG_SIMPLE: u8 = 0;
+pub(crate) const TAG_ERROR: u8 = 1;
+pub(crate) const TAG_BULK: u8 = 2;
+const TAG_NULL_BULK: u8 = 3;
+const TAG_ARRAY: u8 = 4;
+const TAG_NULL_ARRAY: u8 = 5;
+
+/// Decode one Skyhash frame (the byte *after* `MAGIC`) from `buf`. Like the
+/// RESP parser, this only ever reads `buf`; an incomplete frame returns
+/// `Ok(None)` without consuming anything.
+pub(crate) fn try_parse(buf: &[u8]) -> Result<Option<(RespValue, usize)>, RespError> {
+    parse_frame(buf)
+}
+
+fn parse_frame(buf: &[u8]) -> Result<Option<(RespValue, usize)>, RespError> {
+    let tag = match buf.first() {
+        Some(&t) => t,
+        None => return Ok(None),
+    };
+    match tag {
+        TAG_NULL_BULK => Ok(Some((RespValue::BulkString(None), 1))),
+        TAG_NULL_ARRAY => Ok(Some((RespValue::Array(None), 1))),
+        TAG_SIMPLE | TAG_ERROR | TAG_BULK => parse_sized(buf, tag),
+        TAG_ARRAY => parse_array(buf),
+        other => Err(RespError::Protocol(format!("unknown skyhash tag: {other}"))),
+    }
+}
+
+/// Read a little-endian `u32` length from the first 4 bytes of `rest` (the
+/// bytes just after the tag byte), or `None` if fewer than 4 have arrived.
+fn read_len(rest: &[u8]) -> Option<usize> {
+    let bytes: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes) as usize)
+}
+
+fn parse_sized(buf: &[u8], tag: u8) -> Result<Option<(RespValue, usize)>, RespError> {
+    let len = match read_len(&buf[1..]) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let header = 1 + 4;
+    let total = header + len;
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let data = &buf[header..total];
+    let value = match tag {
+        TAG_SIMPLE => RespValue::SimpleString(String::from_utf8_lossy(data).to_string()),
+        TAG_ERROR => RespValue::Error(String::from_utf8_lossy(data).to_string()),
+        TAG_BULK => RespValue::BulkString(Some(Bytes::copy_from_slice(data))),
+        _ => unreachable!("parse_sized only called for sized tags"),
+    };
+    Ok(Some((value, total)))
+}
+
+fn parse_array(buf: &[u8]) -> Result<Option<(RespValue, usize)>, RespError> {
+    let count = match read_len(&buf[1..]) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let count = checked_array_len(count)
+        .map_err(|msg| RespError::Protocol(format!("invalid skyhash array count: {msg}")))?;
+    let mut offset = 1 + 4;
+    let mut items = Vec::with_capacity(count);
+    for _ in 0..count {
+        match parse_frame(&buf[offset..])? {
+            Some((value, n)) => {
+                items.push(value);
+                offset += n;
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some((RespValue::Array(Some(items)), offset)))
+}
+
+/// Encode a value as a Skyhash frame into `out`, mirroring
+/// `encode_resp_into`'s reuse-the-buffer contract.
+pub(crate) fn encode_into(val: &RespValue, out: &mut BytesMut) {
+    match val {
+        RespValue::SimpleString(s) => encode_sized(TAG_SIMPLE, s.as_bytes(), out),
+        RespValue::Error(e) => encode_sized(TAG_ERROR, e.as_bytes(), out),
+        RespValue::BulkString(Some(b)) => encode_sized(TAG_BULK, b, out),
+        RespValue::BulkString(None) => out.put_u8(TAG_NULL_BULK),
+        RespValue::Array(Some(items)) => {
+            out.put_u8(TAG_ARRAY);
+            out.put_u32_le(items.len() as u32);
+            for item in items {
+                encode_into(item, out);
+            }
+        }
+        RespValue::Array(None) => out.put_u8(TAG_NULL_ARRAY),
+    }
+}
+
+fn encode_sized(tag: u8, data: &[u8], out: &mut BytesMut) {
+    out.put_u8(tag);
+    out.put_u32_le(data.len() as u32);
+    out.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sized_resumes_across_every_split() {
+        let mut frame = vec![TAG_BULK];
+        frame.extend_from_slice(&5u32.to_le_bytes());
+        frame.extend_from_slice(b"hello");
+
+        for split_at in 0..frame.len() {
+            assert!(
+                try_parse(&frame[..split_at]).unwrap().is_none(),
+                "prefix of len {split_at} must not parse as complete"
+            );
+        }
+
+        let (value, consumed) = try_parse(&frame).unwrap().unwrap();
+        assert_eq!(consumed, frame.len());
+        match value {
+            RespValue::BulkString(Some(b)) => assert_eq!(b.as_ref(), b"hello"),
+            other => panic!("expected a bulk string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_array_resumes_when_a_later_element_has_not_arrived() {
+        // [null bulk, "OK" simple string], split right before the second
+        // element's own tag byte -- 1 of 2 elements present.
+        let mut frame = vec![TAG_ARRAY];
+        frame.extend_from_slice(&2u32.to_le_bytes());
+        frame.push(TAG_NULL_BULK);
+        frame.push(TAG_SIMPLE);
+        frame.extend_from_slice(&2u32.to_le_bytes());
+        frame.extend_from_slice(b"OK");
+
+        let second_element_len = 1 + 4 + 2;
+        let split_at = frame.len() - second_element_len;
+        assert!(try_parse(&frame[..split_at]).unwrap().is_none());
+
+        let (value, consumed) = try_parse(&frame).unwrap().unwrap();
+        assert_eq!(consumed, frame.len());
+        match value {
+            RespValue::Array(Some(items)) => assert_eq!(items.len(), 2),
+            other => panic!("expected a 2-element array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_tag_is_a_protocol_error_not_an_incomplete_read() {
+        let err = try_parse(&[0xAA]).unwrap_err();
+        assert!(matches!(err, RespError::Protocol(_)));
+    }
+
+    #[test]
+    fn null_variants_round_trip() {
+        let mut out = BytesMut::new();
+        encode_into(&RespValue::BulkString(None), &mut out);
+        encode_into(&RespValue::Array(None), &mut out);
+
+        let (bulk, n) = try_parse(&out).unwrap().unwrap();
+        assert!(matches!(bulk, RespValue::BulkString(None)));
+        let (array, _) = try_parse(&out[n..]).unwrap().unwrap();
+        assert!(matches!(array, RespValue::Array(None)));
+    }
+}
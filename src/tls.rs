@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// Build a `TlsAcceptor` from a PEM certificate chain and PKCS#8 private key
+/// on disk, for the optional `REDIS_RS_TLS_CERT` / `REDIS_RS_TLS_KEY` mode.
+pub(crate) fn build_acceptor(cert_path: &str, key_path: &str) -> std::io::Result<TlsAcceptor> {
+    let cert_chain = certs(&mut BufReader::new(File::open(cert_path)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+
+    let mut keys = pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))?;
+    let key = PrivateKey(keys.pop().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("no PKCS#8 private key found in {}", key_path),
+        )
+    })?);
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
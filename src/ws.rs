@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use bytes::{Buf, BytesMut};
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use rand::RngCore;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use redis_reimplementation_rust::resp::{encode_resp_into, RespValue};
+
+use crate::auth::SessionCipher;
+use crate::command::Command;
+use crate::{auth, skyhash, try_parse_frame, Config, Db, Protocol, RespError};
+
+type WsWriter = SplitSink<WebSocketStream<TcpStream>, Message>;
+
+/// Serve one WebSocket connection with the same RESP/Skyhash pipeline the
+/// plain TCP listener uses: each binary message's bytes are appended to a
+/// buffer and fed through `try_parse_frame`, and every decoded command still
+/// goes through `Command::from_frame` / `execute` unchanged. Authentication
+/// mirrors `handle_connection`: with `REDIS_RS_REQUIREPASS` set, every
+/// command but `AUTH`/`PING` is refused with `NOAUTH` until the client
+/// authenticates, and `AUTH ... UPGRADE` installs the same AEAD
+/// `SessionCipher`. WebSocket already frames messages, so unlike the raw TCP
+/// path there's no length-prefix stream to drain: once a cipher is
+/// installed, each inbound `Binary` message is one whole ciphertext record
+/// and each outbound write seals one whole message.
+pub(crate) async fn handle_ws_connection(
+    stream: TcpStream,
+    db: Db,
+    config: Arc<Config>,
+) -> Result<(), RespError> {
+    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
+    let (mut write, mut read) = ws_stream.split();
+
+    let mut buf = BytesMut::new();
+    let mut out = BytesMut::with_capacity(4096);
+
+    let mut authenticated = config.requirepass.is_none();
+    let mut session_cipher: Option<SessionCipher> = None;
+
+    while let Some(msg) = read.next().await {
+        let msg = msg?;
+        let data = match msg {
+            Message::Binary(data) => data,
+            Message::Close(_) => return Ok(()),
+            // Ping/Pong/Text frames carry no RESP payload; tungstenite already
+            // answers pings automatically, so there's nothing else to do here.
+            _ => continue,
+        };
+
+        let data = match &mut session_cipher {
+            None => data,
+            Some(cipher) => cipher.decrypt(&data)?,
+        };
+        buf.extend_from_slice(&data);
+
+        while let Some((frame, consumed, protocol)) = try_parse_frame(&buf)? {
+            buf.advance(consumed);
+
+            let cmd = match Command::from_frame(frame) {
+                Ok(c) => c,
+                Err(e) => {
+                    let resp = RespValue::Error(format!("ERR {}", e));
+                    send_ws(&mut write, &resp, protocol, &mut out, &mut session_cipher).await?;
+                    continue;
+                }
+            };
+
+            if let Command::Auth { password, upgrade } = cmd {
+                let response = match &config.requirepass {
+                    None => {
+                        RespValue::Error("ERR Client sent AUTH, but no password is set.".into())
+                    }
+                    Some(expected) if auth::verify_password(expected, &password) => {
+                        authenticated = true;
+                        if upgrade {
+                            let mut nonce = [0u8; auth::SERVER_NONCE_LEN];
+                            rand::thread_rng().fill_bytes(&mut nonce);
+                            let nonce_reply =
+                                RespValue::BulkString(Some(bytes::Bytes::copy_from_slice(&nonce)));
+                            // The nonce has to travel in the clear: it's what
+                            // lets the client derive the very key needed to
+                            // decrypt anything after it, including replies.
+                            let mut plaintext: Option<SessionCipher> = None;
+                            send_ws(&mut write, &nonce_reply, protocol, &mut out, &mut plaintext)
+                                .await?;
+                            session_cipher = Some(SessionCipher::new(password.as_bytes(), &nonce));
+                            continue;
+                        }
+                        RespValue::SimpleString("OK".into())
+                    }
+                    Some(_) => RespValue::Error("ERR invalid password".into()),
+                };
+                send_ws(&mut write, &response, protocol, &mut out, &mut session_cipher).await?;
+                continue;
+            }
+
+            if !authenticated && !matches!(cmd, Command::Ping) {
+                let response = RespValue::Error("NOAUTH Authentication required.".into());
+                send_ws(&mut write, &response, protocol, &mut out, &mut session_cipher).await?;
+                continue;
+            }
+
+            let response = cmd.execute(&db).await;
+            send_ws(&mut write, &response, protocol, &mut out, &mut session_cipher).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode `val` into `out` and send it as one WebSocket `Binary` message,
+/// sealing it into an AEAD record first when `cipher` is `Some` (mirroring
+/// `main::send`, but message-at-a-time instead of length-prefixed).
+async fn send_ws(
+    write: &mut WsWriter,
+    val: &RespValue,
+    protocol: Protocol,
+    out: &mut BytesMut,
+    cipher: &mut Option<SessionCipher>,
+) -> Result<(), RespError> {
+    out.clear();
+    match protocol {
+        Protocol::Resp => encode_resp_into(val, out),
+        Protocol::Skyhash => skyhash::encode_into(val, out),
+    }
+    let payload = match cipher {
+        None => out.to_vec(),
+        Some(cipher) => cipher.encrypt(out)?,
+    };
+    write.send(Message::Binary(payload)).await?;
+    Ok(())
+}